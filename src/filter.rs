@@ -0,0 +1,103 @@
+//! Pluggable transforms applied to child output before it's written to the log files.
+
+/// Transforms one chunk of child output at a time. Implementations that need to see
+/// across chunk boundaries (e.g. an escape sequence split across two reads) should keep
+/// that state on `self` rather than assuming a chunk is ever a complete unit.
+pub trait Filter: Send {
+    fn process(&mut self, chunk: &[u8], out: &mut Vec<u8>);
+}
+
+/// Runs `chunk` through every filter in order and returns the final bytes to write.
+pub fn apply_chain(filters: &mut [Box<dyn Filter>], chunk: &[u8]) -> Vec<u8> {
+    let mut current = chunk.to_vec();
+    for f in filters.iter_mut() {
+        let mut out = Vec::with_capacity(current.len());
+        f.process(&current, &mut out);
+        current = out;
+    }
+    current
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    #[default]
+    Plain,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+/// Strips ANSI CSI (`ESC [ ... <final byte>`) and OSC (`ESC ] ... BEL` or `ESC ] ... ESC
+/// \`) escape sequences, keeping a little state across calls so a sequence split across
+/// two reads is still removed correctly.
+#[derive(Default)]
+pub struct AnsiStripFilter {
+    state: AnsiState,
+}
+
+impl Filter for AnsiStripFilter {
+    fn process(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        for &b in chunk {
+            self.state = match self.state {
+                AnsiState::Plain if b == 0x1b => AnsiState::Escape,
+                AnsiState::Plain => {
+                    out.push(b);
+                    AnsiState::Plain
+                }
+                AnsiState::Escape => match b {
+                    b'[' => AnsiState::Csi,
+                    b']' => AnsiState::Osc,
+                    // Any other byte is a single-character escape; it's consumed here.
+                    _ => AnsiState::Plain,
+                },
+                // CSI sequences end at the first byte in the "final byte" range.
+                AnsiState::Csi if (0x40..=0x7e).contains(&b) => AnsiState::Plain,
+                AnsiState::Csi => AnsiState::Csi,
+                AnsiState::Osc if b == 0x07 => AnsiState::Plain, // BEL terminator
+                AnsiState::Osc if b == 0x1b => AnsiState::OscEscape, // maybe a `ST`
+                AnsiState::Osc => AnsiState::Osc,
+                AnsiState::OscEscape if b == b'\\' => AnsiState::Plain, // `ST` terminator
+                AnsiState::OscEscape => AnsiState::Osc,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_csi_sequences() {
+        let mut filter = AnsiStripFilter::default();
+        let mut out = Vec::new();
+        filter.process(b"\x1b[31mred\x1b[0m plain", &mut out);
+        assert_eq!(out, b"red plain");
+    }
+
+    #[test]
+    fn strips_osc_sequence_terminated_by_bel() {
+        let mut filter = AnsiStripFilter::default();
+        let mut out = Vec::new();
+        filter.process(b"\x1b]0;title\x07visible", &mut out);
+        assert_eq!(out, b"visible");
+    }
+
+    #[test]
+    fn strips_osc_sequence_terminated_by_string_terminator() {
+        let mut filter = AnsiStripFilter::default();
+        let mut out = Vec::new();
+        filter.process(b"\x1b]0;title\x1b\\visible", &mut out);
+        assert_eq!(out, b"visible");
+    }
+
+    #[test]
+    fn strips_sequence_split_across_chunks() {
+        let mut filter = AnsiStripFilter::default();
+        let mut out = Vec::new();
+        filter.process(b"before\x1b[3", &mut out);
+        filter.process(b"1mred\x1b[0m after", &mut out);
+        assert_eq!(out, b"beforered after");
+    }
+}
@@ -1,16 +1,27 @@
+mod filter;
+mod pty;
+mod rotate;
+mod signals;
+mod status;
+
 use std::convert::From;
 use std::os::fd::AsRawFd;
 use std::path::PathBuf;
 use std::process::{ExitCode, ExitStatus, Stdio};
+use std::time::Duration;
 
 use anyhow;
 use clap::{Parser, ValueEnum};
+use futures::StreamExt;
 use tokio;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::signal::unix::{signal, SignalKind};
 
+use filter::Filter;
+use rotate::RotationState;
+
 #[derive(Parser, Clone, ValueEnum, Debug)]
 pub enum HandledSignals {
     SIGHUP,
@@ -28,6 +39,13 @@ impl From<&HandledSignals> for SignalKind {
     }
 }
 
+#[derive(Parser, Clone, Copy, PartialEq, ValueEnum, Debug)]
+pub enum RestartPolicy {
+    Never,
+    OnFailure,
+    Always,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -43,10 +61,122 @@ struct Args {
     pid_file: Option<PathBuf>,
     #[arg(long = "sig", value_enum, help="Signal notifiying that the file paths have been rotated", default_value_t = HandledSignals::SIGHUP)]
     rotated_signal: HandledSignals,
+    #[arg(
+        long = "max-size",
+        value_parser = rotate::parse_size,
+        help = "Rotate the output files once they would exceed this size (e.g. `10M`, `512K`)"
+    )]
+    max_size: Option<u64>,
+    #[arg(
+        long = "rotate-interval",
+        value_parser = humantime::parse_duration,
+        help = "Rotate the output files on this interval regardless of size (e.g. `1h`, `30m`)"
+    )]
+    rotate_interval: Option<Duration>,
+    #[arg(
+        long = "keep",
+        default_value_t = 5,
+        help = "Number of rotated files to retain per output"
+    )]
+    keep: usize,
+    #[arg(
+        long = "compress",
+        help = "Gzip rotated files once they've been rotated out"
+    )]
+    compress: bool,
+    #[arg(
+        long = "timeout",
+        value_parser = humantime::parse_duration,
+        help = "Maximum time the wrapped command may run before it is terminated"
+    )]
+    timeout: Option<Duration>,
+    #[arg(
+        long = "kill-grace",
+        value_parser = humantime::parse_duration,
+        default_value = "5s",
+        help = "How long to wait after SIGTERM before escalating to SIGKILL on timeout"
+    )]
+    kill_grace: Duration,
+    #[arg(
+        long = "pty",
+        help = "Run the command attached to a pseudo-terminal instead of a pipe, so it sees an interactive stdout"
+    )]
+    pty: bool,
+    #[arg(
+        long = "strip-ansi",
+        help = "Strip ANSI CSI/OSC escape sequences from captured output before writing it to the log files"
+    )]
+    strip_ansi: bool,
+    #[arg(
+        long = "status-fd",
+        conflicts_with = "status_file",
+        help = "Write newline-delimited JSON lifecycle events to this already-open file descriptor"
+    )]
+    status_fd: Option<i32>,
+    #[arg(
+        long = "status-file",
+        conflicts_with = "status_fd",
+        help = "Write newline-delimited JSON lifecycle events to this file"
+    )]
+    status_file: Option<PathBuf>,
+    #[arg(
+        long = "restart",
+        value_enum,
+        default_value_t = RestartPolicy::Never,
+        help = "Restart the command when it exits: never, on-failure, or always"
+    )]
+    restart: RestartPolicy,
+    #[arg(
+        long = "max-restarts",
+        help = "Maximum number of times to restart the command (unlimited if unset)"
+    )]
+    max_restarts: Option<u32>,
+    #[arg(
+        long = "backoff",
+        value_parser = humantime::parse_duration,
+        default_value = "1s",
+        help = "Base delay before restarting; doubles on each consecutive restart"
+    )]
+    backoff: Duration,
     #[arg(last = true, help = "Command to run")]
     cmd: Vec<String>,
 }
 
+/// Builds the filter chain applied to one output stream, per `Args`.
+fn build_filters(args: &Args) -> Vec<Box<dyn Filter>> {
+    let mut filters: Vec<Box<dyn Filter>> = Vec::new();
+    if args.strip_ansi {
+        filters.push(Box::new(filter::AnsiStripFilter::default()));
+    }
+    filters
+}
+
+/// Distinct exit code returned when `--timeout` terminates the child, so callers can
+/// tell a timeout apart from the command's own exit codes.
+const TIMEOUT_EXIT_CODE: u8 = 124;
+
+/// The delay before the `restarts`-th restart (1-indexed): `base` doubled once per
+/// preceding restart, capped at a `2^10` multiplier so a long-lived flapping command
+/// doesn't end up backing off for hours.
+fn backoff_delay(base: Duration, restarts: u32) -> Duration {
+    base * 2u32.saturating_pow((restarts - 1).min(10))
+}
+
+#[cfg(test)]
+mod backoff_tests {
+    use super::*;
+
+    #[test]
+    fn doubles_per_restart_and_caps() {
+        let base = Duration::from_secs(1);
+        assert_eq!(backoff_delay(base, 1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(base, 2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(base, 3), Duration::from_secs(4));
+        assert_eq!(backoff_delay(base, 12), base * 2u32.pow(10));
+        assert_eq!(backoff_delay(base, 100), base * 2u32.pow(10));
+    }
+}
+
 async fn write_pid_file(p: &PathBuf) -> anyhow::Result<()> {
     let mut pid_file = File::create(p).await?;
     let id = std::process::id().to_string();
@@ -58,10 +188,24 @@ async fn write_pid_file(p: &PathBuf) -> anyhow::Result<()> {
 async fn cleanup(
     result: std::io::Result<ExitStatus>,
     pid_file: &Option<PathBuf>,
+    timeout_code: Option<u8>,
+    status: &mut Option<status::StatusSink>,
 ) -> anyhow::Result<ExitCode> {
     if let Some(p) = pid_file {
         tokio::fs::remove_file(p).await?;
     }
+    if let Some(sink) = status {
+        use std::os::unix::process::ExitStatusExt;
+        let (code, signal) = match &result {
+            Ok(status) => (status.code(), status.signal()),
+            Err(_) => (None, None),
+        };
+        sink.emit(status::Event::Exited { code, signal }).await?;
+    }
+    if let Some(code) = timeout_code {
+        result?;
+        return Ok(ExitCode::from(code));
+    }
     return Ok(ExitCode::from(
         result?.code().expect("No exit code for process") as u8,
     ));
@@ -73,6 +217,25 @@ fn check_for_stale_handle(f: &File) -> anyhow::Result<bool> {
     return Ok(stats.st_nlink > 0);
 }
 
+/// Syncs and rotates `path`'s handle per `state`, then reopens it fresh and resets the
+/// byte counter. Used both for size-triggered rotation and the `--rotate-interval` timer.
+async fn rotate_now(
+    writer: &mut File,
+    path: &PathBuf,
+    state: &mut RotationState,
+    status: &mut Option<status::StatusSink>,
+) -> anyhow::Result<()> {
+    writer.sync_all().await?;
+    rotate::rotate_file(path, state).await?;
+    *writer = File::options().append(true).create(true).open(path).await?;
+    state.reset();
+    if let Some(sink) = status {
+        sink.emit(status::Event::Rotated { path: path.clone() })
+            .await?;
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<ExitCode> {
     let args = Args::parse();
@@ -81,29 +244,21 @@ async fn main() -> anyhow::Result<ExitCode> {
     // Setup our signal hook.
     let handled_sig: SignalKind = (&args.rotated_signal).into();
     let mut rotation_signal_stream = signal(handled_sig)?;
-    let mut sigterm_stream = signal(SignalKind::terminate())?;
-    let mut sigquit_stream = signal(SignalKind::quit())?;
-    let mut sigint_stream = signal(SignalKind::interrupt())?;
+    let mut signal_forwarder = signals::build_forwarder(handled_sig)?;
     // Setup our output wiring.
-    let app_name = match args.cmd.first() {
-        Some(n) => n,
-        None => return Err(anyhow::anyhow!("No command specified")),
+    let app_name = args
+        .cmd
+        .first()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("No command specified"))?;
+    let cmd_args: Vec<String> = args.cmd.iter().skip(1).cloned().collect();
+    let mut status = if let Some(fd) = args.status_fd {
+        Some(status::StatusSink::open_fd(fd)?)
+    } else if let Some(p) = &args.status_file {
+        Some(status::StatusSink::open_path(p).await?)
+    } else {
+        None
     };
-    let mut child = Command::new(app_name)
-        .args(args.cmd.into_iter().skip(1).collect::<Vec<String>>())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-    let mut stdout_reader = child
-        .stdout
-        .take()
-        .expect("no valid stdout from command available");
-    let mut stdout_buffer = [0; 8 * 1024];
-    let mut stderr_reader = child
-        .stderr
-        .take()
-        .expect("no valid stderr from command available");
-    let mut stderr_buffer = [0; 8 * 1024];
 
     let mut stderr_writer = File::options()
         .append(true)
@@ -115,117 +270,257 @@ async fn main() -> anyhow::Result<ExitCode> {
         .create(true)
         .open(stdout_path)
         .await?;
+    let mut stdout_rotation = RotationState::new(args.max_size, args.keep, args.compress);
+    let mut stderr_rotation = RotationState::new(args.max_size, args.keep, args.compress);
+    // Each stream gets its own filter instances, since filter state (e.g. a
+    // partially-seen escape sequence) must not leak across streams.
+    let mut stdout_filters = build_filters(&args);
+    let mut stderr_filters = build_filters(&args);
+    // `tokio::time::interval` fires its first tick immediately; since these files were
+    // just opened and are still empty, that would rotate them before the child has
+    // written anything. Start the first tick a full interval out instead.
+    let mut rotate_interval_timer = args
+        .rotate_interval
+        .map(|d| tokio::time::interval_at(tokio::time::Instant::now() + d, d));
     // TODO(jwall): Write our pidfile somehwere
     if let Some(p) = &args.pid_file {
         write_pid_file(p).await?
     }
-    // TODO(jwall): Forward all other signals to the running process.
-    loop {
+
+    let mut restarts = 0u32;
+    'supervisor: loop {
+        // In `--pty` mode the child's stdin/stdout/stderr are wired to the pty slave by
+        // `Pty::spawn_with` itself, so we only set up piped stdio otherwise. `spawn_with`
+        // also performs the actual `spawn()` for `--pty`, since it must keep its copy of
+        // the slave fd open until the fork has happened.
+        let mut command = Command::new(&app_name);
+        command.args(cmd_args.clone());
+        let (mut child, mut pty_handle) = if args.pty {
+            let (pty, child) = pty::Pty::spawn_with(&mut command)?;
+            (child, Some(pty))
+        } else {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            (command.spawn()?, None)
+        };
+        if let Some(pty) = &pty_handle {
+            pty.sync_window_size()?;
+        }
+        if let Some(sink) = &mut status {
+            if let Some(pid) = child.id() {
+                sink.emit(status::Event::Started { pid }).await?;
+            }
+        }
+        let mut stdout_reader = (!args.pty).then(|| {
+            child
+                .stdout
+                .take()
+                .expect("no valid stdout from command available")
+        });
+        let mut stdout_buffer = [0; 8 * 1024];
+        let mut stderr_reader = (!args.pty).then(|| {
+            child
+                .stderr
+                .take()
+                .expect("no valid stderr from command available")
+        });
+        let mut stderr_buffer = [0; 8 * 1024];
+        let mut pty_buffer = [0; 8 * 1024];
+        // Watchdog state for `--timeout`: armed once per run, then re-armed as a shorter
+        // grace timer once the SIGTERM has been sent.
+        let mut timeout_sleep = args.timeout.map(|d| Box::pin(tokio::time::sleep(d)));
+        let mut kill_sleep: Option<std::pin::Pin<Box<tokio::time::Sleep>>> = None;
+        let mut timed_out = false;
+
         // NOTE(zaphar): Each select block will run exclusively of the other blocks using a
         // psuedorandom order.
-        tokio::select! {
-            // wait for a read on stdout
-            out_result = stdout_reader.read(&mut stdout_buffer) => {
-                match out_result {
-                    Ok(n) => {
-                        if !check_for_stale_handle(&stdout_writer)? {
-                            stdout_writer.flush().await?;
-                            stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+        let result = 'run: loop {
+            tokio::select! {
+                // wait for a read on stdout
+                out_result = async { stdout_reader.as_mut().unwrap().read(&mut stdout_buffer).await },
+                    if stdout_reader.is_some() => {
+                    match out_result {
+                        Ok(n) => {
+                            let filtered = filter::apply_chain(&mut stdout_filters, &stdout_buffer[0..n]);
+                            if !check_for_stale_handle(&stdout_writer)? {
+                                stdout_writer.flush().await?;
+                                stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                            }
+                            if stdout_rotation.should_rotate(filtered.len()) {
+                                rotate_now(&mut stdout_writer, stdout_path, &mut stdout_rotation, &mut status).await?;
+                            }
+                            if let Err(_) = stdout_writer.write(&filtered).await {
+                                stdout_writer.flush().await?;
+                                stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                            }
+                            stdout_rotation.record_write(filtered.len());
+                        },
+                        Err(_) => {
+                            // TODO(zaphar): This likely means the command has broken badly. We should
+                            // do the right thing here.
+                            let result = child.wait().await;
+                            break 'run result;
+                        },
+                    }
+                }
+                // wait for a read on stderr
+                err_result = async { stderr_reader.as_mut().unwrap().read(&mut stderr_buffer).await },
+                    if stderr_reader.is_some() => {
+                    match err_result {
+                        Ok(n) => {
+                            let filtered = filter::apply_chain(&mut stderr_filters, &stderr_buffer[0..n]);
+                            if !check_for_stale_handle(&stderr_writer)? {
+                                stderr_writer.flush().await?;
+                                stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
+                            }
+                            if stderr_rotation.should_rotate(filtered.len()) {
+                                rotate_now(&mut stderr_writer, stderr_path, &mut stderr_rotation, &mut status).await?;
+                            }
+                            if let Err(_) = stderr_writer.write(&filtered).await {
+                                stderr_writer.flush().await?;
+                                stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
+                            }
+                            stderr_rotation.record_write(filtered.len());
+                        },
+                        Err(_) => {
+                            // TODO(zaphar): This likely means the command has broken badly. We should
+                            // do the right thing here..
+                            let result = child.wait().await;
+                            break 'run result;
+                        },
+                    }
+                }
+                // wait for a read on the pty master (combined stdout+stderr in `--pty` mode)
+                pty_result = async { pty_handle.as_mut().unwrap().master.read(&mut pty_buffer).await },
+                    if pty_handle.is_some() => {
+                    match pty_result {
+                        Ok(n) => {
+                            let filtered = filter::apply_chain(&mut stdout_filters, &pty_buffer[0..n]);
+                            if !check_for_stale_handle(&stdout_writer)? {
+                                stdout_writer.flush().await?;
+                                stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                            }
+                            if stdout_rotation.should_rotate(filtered.len()) {
+                                rotate_now(&mut stdout_writer, stdout_path, &mut stdout_rotation, &mut status).await?;
+                            }
+                            if let Err(_) = stdout_writer.write(&filtered).await {
+                                stdout_writer.flush().await?;
+                                stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                            }
+                            stdout_rotation.record_write(filtered.len());
+                        },
+                        Err(_) => {
+                            // The slave end closed, which usually means the child exited.
+                            let result = child.wait().await;
+                            break 'run result;
+                        },
+                    }
+                }
+                _ = rotation_signal_stream.recv() => {
+                    // on sighub sync and reopen our files
+                    // NOTE(zaphar): This will cause the previously opened handles to get
+                    // dropped which will cause them to close assuming all the io has finished. This is why we sync
+                    // before reopening the files.
+                    // TODO(zaphar): These should do something in the event of an error
+                    _ = stderr_writer.sync_all().await;
+                    _ = stdout_writer.sync_all().await;
+                    stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
+                    stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                    if let Some(sink) = &mut status {
+                        sink.emit(status::Event::Rotated { path: stdout_path.clone() }).await?;
+                        sink.emit(status::Event::Rotated { path: stderr_path.clone() }).await?;
+                    }
+                }
+                _ = async { rotate_interval_timer.as_mut().expect("interval timer").tick().await },
+                    if rotate_interval_timer.is_some() => {
+                    // The interval elapsed, so rotate both outputs regardless of size.
+                    rotate_now(&mut stdout_writer, stdout_path, &mut stdout_rotation, &mut status).await?;
+                    rotate_now(&mut stderr_writer, stderr_path, &mut stderr_rotation, &mut status).await?;
+                }
+                _ = async { timeout_sleep.as_mut().expect("timeout timer").await },
+                    if timeout_sleep.is_some() && !timed_out => {
+                    // The watchdog deadline passed. Forward SIGTERM and arm the grace timer;
+                    // if the child hasn't exited by the time that fires we escalate to SIGKILL.
+                    use nix::{
+                        sys::signal::{kill, Signal::SIGTERM},
+                        unistd::Pid,
+                    };
+                    if let Some(pid) = child.id() {
+                        if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGTERM) {
+                            eprintln!("Failed to forward SIGTERM to timed out child process: {}", e);
                         }
-                        if let Err(_) = stdout_writer.write(&stdout_buffer[0..n]).await {
-                            stdout_writer.flush().await?;
-                            stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
+                    }
+                    timed_out = true;
+                    if let Some(sink) = &mut status {
+                        sink.emit(status::Event::TimedOut).await?;
+                    }
+                    kill_sleep = Some(Box::pin(tokio::time::sleep(args.kill_grace)));
+                }
+                _ = async { kill_sleep.as_mut().expect("kill grace timer").await },
+                    if kill_sleep.is_some() => {
+                    // The grace period elapsed without the child exiting; force it.
+                    use nix::{
+                        sys::signal::{kill, Signal::SIGKILL},
+                        unistd::Pid,
+                    };
+                    if let Some(pid) = child.id() {
+                        if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGKILL) {
+                            eprintln!("Failed to forward SIGKILL to timed out child process: {}", e);
                         }
-                    },
-                    Err(_) => {
-                        // TODO(zaphar): This likely means the command has broken badly. We should
-                        // do the right thing here.
-                        let result = child.wait().await;
-                        return cleanup(result, &args.pid_file).await;
-                    },
+                    }
+                    kill_sleep = None;
                 }
-            }
-            // wait for a read on stderr
-            err_result = stderr_reader.read(&mut stderr_buffer) => {
-                match err_result {
-                    Ok(n) => {
-                        if !check_for_stale_handle(&stderr_writer)? {
-                            stderr_writer.flush().await?;
-                            stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
+                Some(sig) = signal_forwarder.next() => {
+                    // NOTE(zaphar): This is a giant hack.
+                    // If https://github.com/tokio-rs/tokio/issues/3379 ever get's implemented it will become
+                    // unnecessary.
+                    use nix::{sys::signal::{kill, Signal}, unistd::Pid};
+                    if sig == Signal::SIGWINCH && pty_handle.is_some() {
+                        // In `--pty` mode a resize of clio's own terminal should resize the
+                        // slave instead of being relayed as a raw signal.
+                        if let Some(pty) = &pty_handle {
+                            if let Err(e) = pty.sync_window_size() {
+                                eprintln!("Failed to resize pty: {}", e);
+                            }
                         }
-                        if let Err(_) = stderr_writer.write(&stderr_buffer[0..n]).await {
-                            stderr_writer.flush().await?;
-                            stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
+                    } else if let Some(pid) = child.id() {
+                        // If the child hasn't already completed, relay whatever signal we received.
+                        if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), sig) {
+                            eprintln!("Failed to forward {:?} to child process: {}", sig, e);
                         }
-                    },
-                    Err(_) => {
-                        // TODO(zaphar): This likely means the command has broken badly. We should
-                        // do the right thing here..
-                        let result = child.wait().await;
-                        return cleanup(result, &args.pid_file).await;
-                    },
-                }
-            }
-            _ = rotation_signal_stream.recv() => {
-                // on sighub sync and reopen our files
-                // NOTE(zaphar): This will cause the previously opened handles to get
-                // dropped which will cause them to close assuming all the io has finished. This is why we sync
-                // before reopening the files.
-                // TODO(zaphar): These should do something in the event of an error
-                _ = stderr_writer.sync_all().await;
-                _ = stdout_writer.sync_all().await;
-                stderr_writer = File::options().append(true).create(true).open(stderr_path).await?;
-                stdout_writer = File::options().append(true).create(true).open(stdout_path).await?;
-            }
-            _ = sigterm_stream.recv() => {
-                // NOTE(zaphar): This is a giant hack.
-                // If https://github.com/tokio-rs/tokio/issues/3379 ever get's implemented it will become
-                // unnecessary.
-                use nix::{
-                    sys::signal::{kill, Signal::SIGTERM},
-                    unistd::Pid,
-                };
-                if let Some(pid) = child.id() {
-                    // If the child hasn't already completed, send a SIGTERM.
-                    if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGTERM) {
-                        eprintln!("Failed to forward SIGTERM to child process: {}", e);
                     }
                 }
-            }
-            _ = sigquit_stream.recv() => {
-                // NOTE(zaphar): This is a giant hack.
-                // If https://github.com/tokio-rs/tokio/issues/3379 ever get's implemented it will become
-                // unnecessary.
-                use nix::{
-                    sys::signal::{kill, Signal::SIGQUIT},
-                    unistd::Pid,
-                };
-                if let Some(pid) = child.id() {
-                    // If the child hasn't already completed, send a SIGTERM.
-                    if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGQUIT) {
-                        eprintln!("Failed to forward SIGQUIT to child process: {}", e);
-                    }
+                result = child.wait() => {
+                    // The child has finished
+                    break 'run result;
                 }
             }
-            _ = sigint_stream.recv() => {
-                // NOTE(zaphar): This is a giant hack.
-                // If https://github.com/tokio-rs/tokio/issues/3379 ever get's implemented it will become
-                // unnecessary.
-                use nix::{
-                    sys::signal::{kill, Signal::SIGINT},
-                    unistd::Pid,
-                };
-                if let Some(pid) = child.id() {
-                    // If the child hasn't already completed, send a SIGTERM.
-                    if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGINT) {
-                        eprintln!("Failed to forward SIGINT to child process: {}", e);
-                    }
+        };
+
+        // A run that `--timeout` killed is never a candidate for restart: the watchdog's
+        // job is to bound how long the command may run, and silently respawning it under
+        // `--restart` would defeat that contract (the caller would never see `124`).
+        let should_restart = !timed_out
+            && match args.restart {
+                RestartPolicy::Never => false,
+                RestartPolicy::OnFailure => {
+                    !matches!(&result, Ok(exit_status) if exit_status.success())
                 }
+                RestartPolicy::Always => true,
             }
-            result = child.wait() => {
-                // The child has finished
-                return cleanup(result, &args.pid_file).await;
-            }
+            && args.max_restarts.is_none_or(|max| restarts < max);
+
+        if !should_restart {
+            return cleanup(
+                result,
+                &args.pid_file,
+                timed_out.then_some(TIMEOUT_EXIT_CODE),
+                &mut status,
+            )
+            .await;
         }
+
+        restarts += 1;
+        tokio::time::sleep(backoff_delay(args.backoff, restarts)).await;
+        continue 'supervisor;
     }
 }
@@ -0,0 +1,88 @@
+//! `--pty` support: gives the wrapped command a pseudo-terminal instead of a pipe, so
+//! programs that detect an interactive stdout (colorized, line-buffered output) behave
+//! the way they would run directly in a terminal.
+use std::os::fd::AsRawFd;
+
+use nix::libc;
+use nix::pty::{openpty, OpenptyResult};
+use nix::unistd::setsid;
+use tokio::fs::File;
+use tokio::process::Command;
+
+/// The master side of an allocated pty, wired up as a child's combined
+/// stdin/stdout/stderr.
+pub struct Pty {
+    pub master: File,
+    master_fd: std::os::fd::RawFd,
+}
+
+impl Pty {
+    /// Allocates a pty pair, configures `cmd` to run with the slave end as its
+    /// controlling terminal, and spawns it, putting the child in its own session so
+    /// `TIOCSCTTY` succeeds.
+    ///
+    /// Spawning has to happen here rather than being left to the caller: our copy of the
+    /// slave fd must stay open until `fork` has actually happened (so the forked child
+    /// inherits it), and closing it a moment too early turns the `dup2` in `pre_exec` into
+    /// an `EBADF`.
+    pub fn spawn_with(cmd: &mut Command) -> anyhow::Result<(Self, tokio::process::Child)> {
+        let OpenptyResult { master, slave } = openpty(None, None)?;
+        let master_fd = master.as_raw_fd();
+        let slave_fd = slave.as_raw_fd();
+
+        // SAFETY: this closure runs in the forked child between `fork` and `exec`, so it
+        // may only call async-signal-safe functions.
+        unsafe {
+            cmd.pre_exec(move || {
+                setsid().map_err(std::io::Error::from)?;
+                if libc::ioctl(slave_fd, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                    if libc::dup2(slave_fd, target) < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if slave_fd > libc::STDERR_FILENO {
+                    libc::close(slave_fd);
+                }
+                // The child has no business holding the master side open; without this it
+                // inherits a live fd to its own controlling terminal's master across exec.
+                if master_fd > libc::STDERR_FILENO {
+                    libc::close(master_fd);
+                }
+                Ok(())
+            });
+        }
+        cmd.stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+
+        let child = cmd.spawn()?;
+        // Now that `fork` has happened and the child has its own copy of the slave fd, our
+        // copy isn't needed anymore.
+        drop(slave);
+        let master = File::from_std(std::fs::File::from(master));
+        Ok((Self { master, master_fd }, child))
+    }
+
+    /// Copies `clio`'s own controlling terminal size onto the pty. Called once at
+    /// startup and again every time `clio` receives `SIGWINCH`, so the child's programs
+    /// reflow to match the real terminal.
+    pub fn sync_window_size(&self) -> anyhow::Result<()> {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        // If clio itself isn't attached to a tty, there's nothing to inherit; leave the
+        // pty at openpty's default size.
+        let tty = match std::fs::File::open("/dev/tty") {
+            Ok(tty) => tty,
+            Err(_) => return Ok(()),
+        };
+        if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut ws) } != 0 {
+            return Ok(());
+        }
+        if unsafe { libc::ioctl(self.master_fd, libc::TIOCSWINSZ, &ws) } < 0 {
+            return Err(anyhow::anyhow!(std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Size- and time-based rotation for the files `clio` writes child output to.
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+/// Parses human friendly byte sizes like `10M`, `512K`, or `2G` into a raw byte count.
+/// A bare number (e.g. `1048576`) is interpreted as a byte count.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let (digits, mult) = match input.chars().last() {
+        Some(c @ ('K' | 'k')) => (&input[..input.len() - c.len_utf8()], 1024u64),
+        Some(c @ ('M' | 'm')) => (&input[..input.len() - c.len_utf8()], 1024u64 * 1024),
+        Some(c @ ('G' | 'g')) => (&input[..input.len() - c.len_utf8()], 1024u64 * 1024 * 1024),
+        _ => (input, 1u64),
+    };
+    let base: u64 = digits.trim().parse().map_err(|_| {
+        format!(
+            "Invalid size `{}`. Expected e.g. `10M`, `512K`, or a byte count.",
+            input
+        )
+    })?;
+    Ok(base * mult)
+}
+
+/// Tracks the rotation policy and running byte count for a single output file.
+pub struct RotationState {
+    pub max_size: Option<u64>,
+    pub keep: usize,
+    pub compress: bool,
+    bytes_written: u64,
+}
+
+impl RotationState {
+    pub fn new(max_size: Option<u64>, keep: usize, compress: bool) -> Self {
+        Self {
+            max_size,
+            keep,
+            compress,
+            bytes_written: 0,
+        }
+    }
+
+    /// Would writing `additional` more bytes push us past `max_size`?
+    pub fn should_rotate(&self, additional: usize) -> bool {
+        match self.max_size {
+            Some(max) => self.bytes_written + additional as u64 > max,
+            None => false,
+        }
+    }
+
+    pub fn record_write(&mut self, n: usize) {
+        self.bytes_written += n as u64;
+    }
+
+    pub fn reset(&mut self) {
+        self.bytes_written = 0;
+    }
+}
+
+/// Names generation `idx` of `path`'s rotated history. When `compress` is set every
+/// generation on disk carries a `.gz` suffix (compression happens immediately after each
+/// rotation), so the shift loop in `rotate_file` must look for that suffix too or it'll
+/// find nothing to shift and silently recompress over the previous generation.
+fn rotated_path(path: &Path, idx: usize, compress: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", idx));
+    if compress {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Shifts `path.1` -> `path.2` ... `path.(keep-1)` -> `path.(keep)`, dropping anything
+/// beyond `keep`, then moves `path` -> `path.1`, optionally gzip compressing it.
+/// With `keep == 0` nothing is retained and `path` is removed instead. Does not touch
+/// `path` itself beyond the rename (or that removal); the caller is responsible for
+/// reopening it afterwards.
+pub async fn rotate_file(path: &PathBuf, state: &RotationState) -> anyhow::Result<()> {
+    if state.keep == 0 {
+        // Nothing to retain, so the current contents are simply discarded.
+        let _ = tokio::fs::remove_file(path).await;
+        return Ok(());
+    }
+    let oldest = rotated_path(path, state.keep, state.compress);
+    let _ = tokio::fs::remove_file(&oldest).await;
+    for i in (1..state.keep).rev() {
+        let from = rotated_path(path, i, state.compress);
+        let to = rotated_path(path, i + 1, state.compress);
+        if tokio::fs::metadata(&from).await.is_ok() {
+            tokio::fs::rename(&from, &to).await?;
+        }
+    }
+    if tokio::fs::metadata(path).await.is_ok() {
+        // The freshly rotated generation always starts out uncompressed, regardless of
+        // `state.compress`; `compress_file` below turns it into `path.1.gz` so it lines up
+        // with the naming the shift loop expects on the next rotation.
+        let first = rotated_path(path, 1, false);
+        tokio::fs::rename(path, &first).await?;
+        if state.compress {
+            compress_file(first).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Gzips `path` in place, leaving `path.gz` and removing the uncompressed original.
+async fn compress_file(path: PathBuf) -> anyhow::Result<()> {
+    let mut src = File::open(&path)
+        .await
+        .with_context(|| format!("opening rotated file {:?} for compression", path))?;
+    let mut contents = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut src, &mut contents).await?;
+    drop(src);
+
+    let gz_bytes = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&contents)?;
+        encoder.finish()
+    })
+    .await??;
+
+    let mut gz_name = path.as_os_str().to_os_string();
+    gz_name.push(".gz");
+    let mut gz_file = File::create(PathBuf::from(gz_name)).await?;
+    gz_file.write_all(&gz_bytes).await?;
+    gz_file.sync_all().await?;
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_accepts_suffixes_and_bare_numbers() {
+        assert_eq!(parse_size("1048576").unwrap(), 1048576);
+        assert_eq!(parse_size("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("10M").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("10k").unwrap(), 10 * 1024);
+    }
+
+    #[test]
+    fn parse_size_rejects_garbage() {
+        assert!(parse_size("not-a-size").is_err());
+    }
+
+    #[tokio::test]
+    async fn rotate_file_with_compress_keeps_all_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let state = RotationState::new(None, 3, true);
+
+        // Rotate through more generations than `keep` retains, to exercise both the
+        // shift loop and the drop of the oldest generation.
+        for generation in 0..5 {
+            tokio::fs::write(&path, format!("generation {generation}"))
+                .await
+                .unwrap();
+            rotate_file(&path, &state).await.unwrap();
+        }
+
+        // The three most recent rotated generations should still be present, as separate
+        // `.gz` files rather than all collapsed into one.
+        for idx in 1..=3 {
+            assert!(
+                rotated_path(&path, idx, true).exists(),
+                "generation {idx} missing after rotation"
+            );
+        }
+        // Nothing beyond `keep` should have survived.
+        assert!(!rotated_path(&path, 4, true).exists());
+        // And the shift loop should never have left a stale uncompressed file behind.
+        assert!(!rotated_path(&path, 1, false).exists());
+    }
+}
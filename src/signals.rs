@@ -0,0 +1,38 @@
+//! Generic forwarding of job-control and termination signals to the wrapped child.
+use futures::stream::select_all;
+use futures::{Stream, StreamExt};
+use nix::sys::signal::Signal as NixSignal;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_stream::wrappers::SignalStream;
+
+/// The full set of signals `clio` relays to the child, paired with the `nix` signal used
+/// to actually deliver them. `--sig` is handled separately (it drives rotation, not
+/// forwarding) and is filtered out of this set by `build_forwarder`.
+fn candidates() -> Vec<(SignalKind, NixSignal)> {
+    vec![
+        (SignalKind::terminate(), NixSignal::SIGTERM),
+        (SignalKind::quit(), NixSignal::SIGQUIT),
+        (SignalKind::interrupt(), NixSignal::SIGINT),
+        (SignalKind::from_raw(nix::libc::SIGTSTP), NixSignal::SIGTSTP),
+        (SignalKind::from_raw(nix::libc::SIGCONT), NixSignal::SIGCONT),
+        (SignalKind::window_change(), NixSignal::SIGWINCH),
+        (SignalKind::user_defined1(), NixSignal::SIGUSR1),
+        (SignalKind::user_defined2(), NixSignal::SIGUSR2),
+    ]
+}
+
+/// Builds a single merged stream of every forwardable signal `clio` receives, with
+/// `exclude` (the rotation signal) removed from the set. Each item is the `nix::Signal`
+/// to relay to the child.
+pub fn build_forwarder(
+    exclude: SignalKind,
+) -> anyhow::Result<impl Stream<Item = NixSignal> + Unpin> {
+    let streams = candidates()
+        .into_iter()
+        .filter(|(kind, _)| *kind != exclude)
+        .map(|(kind, sig)| -> anyhow::Result<_> {
+            Ok(SignalStream::new(signal(kind)?).map(move |_| sig))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(select_all(streams))
+}
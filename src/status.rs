@@ -0,0 +1,96 @@
+//! Structured lifecycle events, written as newline-delimited JSON to an optional status
+//! file descriptor or file, so a supervising process can observe `clio` without having
+//! to scrape the logs it writes.
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event")]
+pub enum Event {
+    Started {
+        pid: u32,
+    },
+    Rotated {
+        path: PathBuf,
+    },
+    TimedOut,
+    Exited {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// Where `Event`s get written. Opened once up front and kept for the life of the
+/// process.
+pub struct StatusSink {
+    file: tokio::fs::File,
+}
+
+impl StatusSink {
+    /// Takes ownership of an already-open fd (e.g. one the caller set up with shell fd
+    /// redirection) and writes events to it.
+    pub fn open_fd(fd: i32) -> anyhow::Result<Self> {
+        use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+        use std::os::fd::FromRawFd;
+        // Mark the fd close-on-exec so the wrapped (and any restarted) child doesn't
+        // inherit unintended access to clio's internal status channel.
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        // SAFETY: the caller is responsible for `fd` being a valid, open, and otherwise
+        // unused file descriptor for the lifetime of this process.
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Ok(Self {
+            file: tokio::fs::File::from_std(file),
+        })
+    }
+
+    pub async fn open_path(path: &PathBuf) -> anyhow::Result<Self> {
+        let file = tokio::fs::File::options()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await?;
+        Ok(Self { file })
+    }
+
+    pub async fn emit(&mut self, event: Event) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_json_shape_is_a_stable_wire_contract() {
+        assert_eq!(
+            serde_json::to_string(&Event::Started { pid: 1 }).unwrap(),
+            r#"{"event":"Started","pid":1}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::Rotated {
+                path: PathBuf::from("/var/log/out.log")
+            })
+            .unwrap(),
+            r#"{"event":"Rotated","path":"/var/log/out.log"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::TimedOut).unwrap(),
+            r#"{"event":"TimedOut"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::Exited {
+                code: Some(0),
+                signal: None
+            })
+            .unwrap(),
+            r#"{"event":"Exited","code":0,"signal":null}"#
+        );
+    }
+}